@@ -2,12 +2,72 @@ use crate::cell::Cell;
 use crate::types::Point;
 use rand::Rng;
 use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Holds the two cell buffers `Grid::step_life` swaps between so a tick never
+/// has to allocate a fresh `Vec<Cell>`: the update reads from the front
+/// buffer and writes the next state straight into the back buffer, then
+/// `swap` just flips which one is current.
+struct DoubleBuffer {
+    buffers: [Vec<Cell>; 2],
+    front: usize,
+}
+
+impl DoubleBuffer {
+    fn new(size: usize) -> Self {
+        Self {
+            buffers: [vec![Cell::new(false); size], vec![Cell::new(false); size]],
+            front: 0,
+        }
+    }
+
+    fn front(&self) -> &[Cell] {
+        &self.buffers[self.front]
+    }
+
+    fn front_mut(&mut self) -> &mut Vec<Cell> {
+        &mut self.buffers[self.front]
+    }
+
+    /// Borrows the front buffer immutably and the back buffer mutably at the
+    /// same time, so the parallel update can read the current state while
+    /// writing the next one in place.
+    fn split_mut(&mut self) -> (&[Cell], &mut [Cell]) {
+        let front = self.front;
+        let (a, b) = self.buffers.split_at_mut(1);
+        if front == 0 {
+            (&a[0], &mut b[0])
+        } else {
+            (&b[0], &mut a[0])
+        }
+    }
+
+    fn swap(&mut self) {
+        self.front = 1 - self.front;
+    }
+}
+
+/// Configuration for the optional agent/resource metabolism mode layered on
+/// top of the classic Game-of-Life rules, see `Grid::new`.
+#[derive(Clone, Copy)]
+pub struct AgentModeConfig {
+    /// Probability a cell's resources replenish by 1 on a given tick.
+    pub resource_regen_probability: f64,
+    /// Maximum resources a single cell can hold.
+    pub resource_cap: u32,
+    /// Energy an agent pays to survive a tick.
+    pub metabolic_cost: u32,
+    /// Energy threshold above which an agent splits into a neighbour.
+    pub birth_threshold: u32,
+    /// Energy a newly-live cell starts with whenever a pattern is (re)loaded.
+    pub initial_energy: u32,
+}
 
 pub struct Grid {
     width: usize,
     height: usize,
     pub initial_cells: Vec<Cell>,
-    pub cells: Vec<Cell>,
+    cells: DoubleBuffer,
     pub cells_probabilities: Vec<usize>,
     pub iteration: usize,
     pub launch_count: usize,
@@ -15,10 +75,24 @@ pub struct Grid {
     pub max_launch_count: usize,
     pub dead_probability: f64,
     pub alive_probability: f64,
+    birth: [bool; 9],
+    survival: [bool; 9],
+    seed: u64,
+    agent_mode: Option<AgentModeConfig>,
+    resources: Vec<u32>,
+    energy: Vec<u32>,
+    // Scratch buffers `step_agents` computes the next agent tick into, reused
+    // in place every launch x iteration instead of being reallocated (the
+    // same reason `DoubleBuffer` exists for `step_life`).
+    scratch_alive: Vec<bool>,
+    next_alive: Vec<bool>,
+    next_energy: Vec<u32>,
+    next_resources: Vec<u32>,
 }
 
 impl Grid {
     // Width and height of the Grid
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: usize,
         height: usize,
@@ -26,12 +100,15 @@ impl Grid {
         max_launch_count: usize,
         dead_probability: f64,
         alive_probability: f64,
+        rule: &str,
+        seed: u64,
+        agent_mode: Option<AgentModeConfig>,
     ) -> Self {
-        Self {
+        let mut grid = Self {
             width,
             height,
             initial_cells: vec![Cell::new(false); width * height],
-            cells: vec![Cell::new(false); width * height],
+            cells: DoubleBuffer::new(width * height),
             cells_probabilities: vec![0; width * height],
             iteration: 0,
             launch_count: 0,
@@ -39,136 +116,356 @@ impl Grid {
             max_launch_count,
             dead_probability,
             alive_probability,
+            birth: [false; 9],
+            survival: [false; 9],
+            seed,
+            agent_mode,
+            resources: vec![0; width * height],
+            energy: vec![0; width * height],
+            scratch_alive: vec![false; width * height],
+            next_alive: vec![false; width * height],
+            next_energy: vec![0; width * height],
+            next_resources: vec![0; width * height],
+        };
+        grid.set_rule(rule).expect("invalid rulestring");
+        grid
+    }
+
+    /// The current state of the grid, i.e. the front buffer.
+    pub fn cells(&self) -> &[Cell] {
+        self.cells.front()
+    }
+
+    /// Sets the birth/survival rule from a rulestring such as "B3/S23" (Conway's
+    /// Game of Life) or "B36/S23" (HighLife). Digits after `B` mark neighbour
+    /// counts that bring a dead cell to life; digits after `S` mark neighbour
+    /// counts that keep a live cell alive. Rejects any digit outside 0-8.
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), String> {
+        let mut birth = [false; 9];
+        let mut survival = [false; 9];
+
+        for part in rule.split('/') {
+            if let Some(digits) = part.strip_prefix('B') {
+                for c in digits.chars() {
+                    birth[Self::rule_digit(c)?] = true;
+                }
+            } else if let Some(digits) = part.strip_prefix('S') {
+                for c in digits.chars() {
+                    survival[Self::rule_digit(c)?] = true;
+                }
+            } else {
+                return Err(format!("invalid rulestring segment: {}", part));
+            }
+        }
+
+        self.birth = birth;
+        self.survival = survival;
+        Ok(())
+    }
+
+    fn rule_digit(c: char) -> Result<usize, String> {
+        match c.to_digit(10) {
+            Some(n) if n <= 8 => Ok(n as usize),
+            _ => Err(format!("invalid neighbour count in rulestring: {}", c)),
         }
     }
     // These functions are using in the main.rs
     pub fn set_state(&mut self, cells_coords: &[Point]) {
-        self.cells = vec![Cell::new(false); self.width * self.height];
+        *self.cells.front_mut() = vec![Cell::new(false); self.width * self.height];
         for &pos in cells_coords.iter() {
             let idx = self.coords_to_index(pos);
-            self.cells[idx].set_state(true);
+            self.cells.front_mut()[idx].set_state(true);
         }
+        self.sync_agent_state();
     }
     pub fn set_initial_state(&mut self, cells_coords: &[Point]) {
         self.initial_cells = vec![Cell::new(false); self.width * self.height];
         for &pos in cells_coords.iter() {
             let idx = self.coords_to_index(pos);
-            self.cells[idx].set_state(true);
+            self.cells.front_mut()[idx].set_state(true);
         }
-        self.initial_cells = self.cells.clone();
+        self.initial_cells = self.cells.front().to_vec();
+        self.sync_agent_state();
     }
 
     pub fn reset_state(&mut self) {
         for i in 0..self.initial_cells.len() {
             let cell = self.initial_cells[i].clone();
-            self.cells[i] = cell;
+            self.cells.front_mut()[i] = cell;
         }
         self.iteration = 0;
+        self.sync_agent_state();
     }
 
-    fn cell_next_state(&self, cell_idx: usize) -> bool {
-        let cell = self.cells[cell_idx].clone();
-        let cell_pos = self.index_to_coords(cell_idx);
-        // Check boundaries and add neighgours
-        let mut num_neighbour_alive = 0;
-        for &x_off in [-1, 0, 1].iter() {
-            for &y_off in [-1, 0, 1].iter() {
-                if x_off == 0 && y_off == 0 {
-                    continue;
+    /// Resets `resources` to empty and `energy` to `initial_energy` for every
+    /// live cell in the front buffer (and to 0 for dead ones). No-op outside
+    /// agent mode. Called whenever a pattern-setting method replaces the
+    /// front buffer and by `reset_state` between launches, so a freshly
+    /// (re)loaded population always starts from a sane, reproducible
+    /// baseline instead of inheriting stale resources/energy.
+    fn sync_agent_state(&mut self) {
+        let config = match self.agent_mode {
+            Some(config) => config,
+            None => return,
+        };
+
+        let alive: Vec<bool> = self.cells.front().iter().map(|c| c.is_alive()).collect();
+        for (idx, is_alive) in alive.into_iter().enumerate() {
+            self.resources[idx] = 0;
+            self.energy[idx] = if is_alive { config.initial_energy } else { 0 };
+        }
+    }
+
+    /// Generates an organic, cave-like initial state: fills every cell alive
+    /// with probability `fill_probability`, then runs `smoothing_passes`
+    /// majority-rule smoothing passes over the torus (a cell becomes alive
+    /// with >=5 alive neighbours, dead with <=3, and is left unchanged at 4).
+    /// The result becomes both `cells` and `initial_cells`. Returns an error
+    /// if `fill_probability` is outside `[0, 1]`.
+    pub fn generate_initial_cave(
+        &mut self,
+        fill_probability: f64,
+        smoothing_passes: usize,
+    ) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&fill_probability) {
+            return Err(format!(
+                "fill_probability must be in [0, 1], got {}",
+                fill_probability
+            ));
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut cells: Vec<Cell> = (0..self.width * self.height)
+            .map(|_| Cell::new(rng.gen_bool(fill_probability)))
+            .collect();
+
+        for _ in 0..smoothing_passes {
+            let mut next = cells.clone();
+            for idx in 0..cells.len() {
+                let alive_neighbours = self
+                    .wrapped_neighbour_indices(idx)
+                    .iter()
+                    .filter(|&&n| cells[n].is_alive())
+                    .count();
+
+                if alive_neighbours >= 5 {
+                    next[idx].set_state(true);
+                } else if alive_neighbours <= 3 {
+                    next[idx].set_state(false);
+                } else {
+                    next[idx] = cells[idx].clone();
                 }
-                let neighbour_pos;
-                let neighbour_coords = (cell_pos.x as isize + x_off, cell_pos.y as isize + y_off);
-
-                // Make torus
-                if neighbour_coords.0 < 0 {
-                    // top-left cell
-                    if neighbour_coords.1 < 0 {
-                        neighbour_pos = Point {
-                            x: self.width - 1,
-                            y: self.height - 1,
-                        }
-                    } else if neighbour_coords.1 > self.height as isize - 1 {
-                        // bottom-left cell
-                        neighbour_pos = Point {
-                            x: self.width - 1,
-                            y: 0,
-                        }
-                    } else {
-                        // left cell
-                        neighbour_pos = Point {
-                            x: self.width - 1,
-                            y: neighbour_coords.1 as usize,
-                        }
-                    }
-                } else if neighbour_coords.0 > self.width as isize - 1 {
-                    if neighbour_coords.1 < 0 {
-                        // top-right cell
-                        neighbour_pos = Point {
-                            x: 0,
-                            y: self.height - 1,
+            }
+            cells = next;
+        }
+
+        *self.cells.front_mut() = cells.clone();
+        self.initial_cells = cells;
+        self.sync_agent_state();
+        Ok(())
+    }
+
+    /// Loads a pattern in the plaintext format (`*`/`O` alive, `.`/space dead,
+    /// one row per line) into the grid, setting both `cells` and
+    /// `initial_cells`. Returns an error if a line is wider than the grid or
+    /// there are more lines than `height`.
+    pub fn from_plaintext(&mut self, text: &str) -> Result<(), String> {
+        let mut cells = vec![Cell::new(false); self.width * self.height];
+        let lines: Vec<&str> = text.lines().collect();
+
+        if lines.len() > self.height {
+            return Err(format!(
+                "plaintext pattern has more rows ({}) than the grid height ({})",
+                lines.len(),
+                self.height
+            ));
+        }
+
+        for (y, line) in lines.iter().enumerate() {
+            if line.chars().count() > self.width {
+                return Err(format!(
+                    "plaintext pattern row {} is wider than the grid width ({})",
+                    y, self.width
+                ));
+            }
+
+            for (x, c) in line.chars().enumerate() {
+                if c == '*' || c == 'O' {
+                    let idx = self.coords_to_index(Point { x, y });
+                    cells[idx].set_state(true);
+                }
+            }
+        }
+
+        *self.cells.front_mut() = cells.clone();
+        self.initial_cells = cells;
+        self.sync_agent_state();
+        Ok(())
+    }
+
+    /// Dumps the current state in the plaintext format (`*` alive, `.` dead),
+    /// one row per line.
+    pub fn to_plaintext(&self) -> String {
+        let cells = self.cells();
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = self.coords_to_index(Point { x, y });
+                out.push(if cells[idx].is_alive() { '*' } else { '.' });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Loads a pattern in the Life RLE format (an `x = W, y = H` header
+    /// followed by a run-length body where `b` is dead, `o` is alive, `$`
+    /// ends a row and `!` ends the pattern), centered on the grid. Sets both
+    /// `cells` and `initial_cells`.
+    pub fn from_rle(&mut self, text: &str) -> Result<(), String> {
+        let mut header = None;
+        let mut body = String::new();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if header.is_none() && line.starts_with('x') {
+                header = Some(line.to_string());
+                continue;
+            }
+            body.push_str(line);
+        }
+
+        let header = header.ok_or_else(|| "missing RLE header".to_string())?;
+        let (pattern_width, pattern_height) = parse_rle_header(&header)?;
+
+        if pattern_width > self.width || pattern_height > self.height {
+            return Err(format!(
+                "RLE pattern ({}x{}) is larger than the grid ({}x{})",
+                pattern_width, pattern_height, self.width, self.height
+            ));
+        }
+
+        let offset_x = (self.width - pattern_width) / 2;
+        let offset_y = (self.height - pattern_height) / 2;
+
+        let mut cells = vec![Cell::new(false); self.width * self.height];
+        let mut x = 0usize;
+        let mut y = 0usize;
+        let mut run_count = 0usize;
+
+        for c in body.chars() {
+            match c {
+                '0'..='9' => {
+                    run_count = run_count * 10 + c.to_digit(10).unwrap() as usize;
+                }
+                'b' | 'o' => {
+                    let count = run_count.max(1);
+                    if c == 'o' {
+                        for _ in 0..count {
+                            if x >= pattern_width || y >= pattern_height {
+                                return Err(format!(
+                                    "RLE pattern body exceeds its declared dimensions ({}x{})",
+                                    pattern_width, pattern_height
+                                ));
+                            }
+                            let idx = self.coords_to_index(Point {
+                                x: offset_x + x,
+                                y: offset_y + y,
+                            });
+                            cells[idx].set_state(true);
+                            x += 1;
                         }
-                    } else if neighbour_coords.1 > self.height as isize - 1 {
-                        // bottom-right cell
-                        neighbour_pos = Point { x: 0, y: 0 }
                     } else {
-                        // right cell
-                        neighbour_pos = Point {
-                            x: 0,
-                            y: neighbour_coords.1 as usize,
+                        x += count;
+                        if x > pattern_width {
+                            return Err(format!(
+                                "RLE pattern body exceeds its declared width ({})",
+                                pattern_width
+                            ));
                         }
                     }
-                } else if neighbour_coords.1 < 0 {
-                    // top cell
-                    neighbour_pos = Point {
-                        x: neighbour_coords.0 as usize,
-                        y: self.height - 1,
-                    }
-                } else if neighbour_coords.1 > self.height as isize - 1 {
-                    // bottom cell
-                    neighbour_pos = Point {
-                        x: neighbour_coords.0 as usize,
-                        y: 0,
-                    }
-                } else {
-                    // Others cells
-                    neighbour_pos = Point {
-                        x: neighbour_coords.0 as usize,
-                        y: neighbour_coords.1 as usize,
-                    };
+                    run_count = 0;
                 }
-
-                let idx = self.coords_to_index(neighbour_pos);
-                if self.cells[idx].is_alive() {
-                    num_neighbour_alive += 1;
+                '$' => {
+                    y += run_count.max(1);
+                    x = 0;
+                    run_count = 0;
+                    if y > pattern_height {
+                        return Err(format!(
+                            "RLE pattern body exceeds its declared height ({})",
+                            pattern_height
+                        ));
+                    }
                 }
+                '!' => break,
+                _ => return Err(format!("invalid RLE token: {}", c)),
             }
         }
-        let mut rnd = rand::thread_rng();
 
-        // Rules https://en.wikipedia.org/wiki/Conway%27s_Game_of_Life
-        if cell.is_alive() && (num_neighbour_alive == 2 || num_neighbour_alive == 3)
-            || (!cell.is_alive() && num_neighbour_alive == 3)
-        {
-            let probability = rnd.gen_range(0.0..1.0);
+        *self.cells.front_mut() = cells.clone();
+        self.initial_cells = cells;
+        self.sync_agent_state();
+        Ok(())
+    }
+
+    /// Returns the indices of the 8 cells wrapped around `cell_idx` on the torus.
+    fn wrapped_neighbour_indices(&self, cell_idx: usize) -> [usize; 8] {
+        wrapped_neighbour_indices(self.width, self.height, cell_idx)
+    }
+
+    /// Labels the connected components of live cells on the torus, treating
+    /// dead cells as background and flood-filling across the 8-connected
+    /// wrapped neighbourhood. Returns a per-cell label (`usize::MAX` for dead
+    /// cells) plus the size of each labelled component.
+    pub fn label_regions(&self) -> (Vec<usize>, Vec<usize>) {
+        let cells = self.cells();
+        let mut labels = vec![usize::MAX; cells.len()];
+        let mut sizes = Vec::new();
 
-            if probability <= self.alive_probability {
-                return true; // alive
+        for start in 0..cells.len() {
+            if !cells[start].is_alive() || labels[start] != usize::MAX {
+                continue;
             }
 
-            return false;
-        }
+            let label = sizes.len();
+            let mut size = 0;
+            let mut queue = VecDeque::new();
+            queue.push_back(start);
+            labels[start] = label;
 
-        let probability = rnd.gen_range(0.0..1.0);
+            while let Some(idx) = queue.pop_front() {
+                size += 1;
+                for &neighbour in self.wrapped_neighbour_indices(idx).iter() {
+                    if cells[neighbour].is_alive() && labels[neighbour] == usize::MAX {
+                        labels[neighbour] = label;
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
 
-        if probability <= self.dead_probability {
-            return false;
+            sizes.push(size);
         }
 
-        return true;
+        (labels, sizes)
+    }
+
+    /// Kills any live connected component smaller than `min_size`.
+    pub fn prune_regions(&mut self, min_size: usize) {
+        let (labels, sizes) = self.label_regions();
+        for (idx, &label) in labels.iter().enumerate() {
+            if label != usize::MAX && sizes[label] < min_size {
+                self.cells.front_mut()[idx].set_state(false);
+            }
+        }
     }
 
     pub fn set_probability(&mut self, idx: usize) {
-        let cell = self.cells[idx].clone();
+        let cell = self.cells.front()[idx].clone();
         if cell.is_alive() {
             self.cells_probabilities[idx] += 1;
         }
@@ -193,23 +490,13 @@ impl Grid {
     }
 
     pub fn update(&mut self) {
-        // Vector of next states. It will match by index
-        // Get next states
-        let next_states = (0..self.cells.len())
-            .into_par_iter()
-            .map(|idx| {
-                // next state
-                self.cell_next_state(idx)
-            })
-            .collect::<Vec<bool>>();
-
-        self.cells = (0..self.cells.len())
-            .into_par_iter()
-            .map(|idx| Cell::new(next_states[idx]))
-            .collect::<Vec<Cell>>();
+        match self.agent_mode {
+            Some(config) => self.step_agents(config),
+            None => self.step_life(),
+        }
 
         if self.iteration == self.max_iterations {
-            for idx in 0..self.cells.len() {
+            for idx in 0..self.cells.front().len() {
                 self.set_probability(idx);
             }
             println!("launches: {}", self.launch_count);
@@ -227,16 +514,435 @@ impl Grid {
         }
     }
 
+    /// The classic Game-of-Life tick: advances every cell per the configured
+    /// birth/survival rule, unaffected by the agent/resource mode.
+    fn step_life(&mut self) {
+        let width = self.width;
+        let height = self.height;
+        let birth = self.birth;
+        let survival = self.survival;
+        let alive_probability = self.alive_probability;
+        let dead_probability = self.dead_probability;
+        let seed = self.seed;
+        let launch_count = self.launch_count as u64;
+        let iteration = self.iteration as u64;
+
+        // Read the front buffer, write the next state straight into the back
+        // buffer, then flip which one is current instead of allocating.
+        let (front, back) = self.cells.split_mut();
+
+        back.par_iter_mut().enumerate().for_each(|(idx, cell)| {
+            let alive = next_cell_state(
+                front,
+                width,
+                height,
+                &birth,
+                &survival,
+                alive_probability,
+                dead_probability,
+                seed,
+                launch_count,
+                iteration,
+                idx,
+            );
+            cell.set_state(alive);
+        });
+
+        self.cells.swap();
+    }
+
+    /// Advances the agent/resource metabolism mode by one tick: resources
+    /// replenish, agents pay their metabolic cost and consume resources to
+    /// refill energy, starve when energy hits zero, and split into a random
+    /// empty wrapped neighbour once energy exceeds `birth_threshold`.
+    fn step_agents(&mut self, config: AgentModeConfig) {
+        let width = self.width;
+        let height = self.height;
+        let seed = self.seed;
+        let launch_count = self.launch_count as u64;
+        let iteration = self.iteration as u64;
+        let len = self.cells.front().len();
+
+        // Resources replenish in place: this is per-cell and independent of
+        // any neighbour, so it can't observe order the way agent actions can.
+        for idx in 0..len {
+            if self.resources[idx] < config.resource_cap
+                && cell_random(seed, launch_count, iteration, idx as u64)
+                    <= config.resource_regen_probability
+            {
+                self.resources[idx] += 1;
+            }
+        }
+
+        // Snapshot the state agents act on this tick and compute the next
+        // state into the grid's own scratch buffers, applying it only after
+        // the whole pass -- mirroring the front/back split `step_life` uses
+        // -- so a death or birth isn't visible to cells processed later in
+        // the same tick. The buffers are fixed-size and reused every launch x
+        // iteration instead of being reallocated.
+        for (idx, cell) in self.cells.front().iter().enumerate() {
+            self.scratch_alive[idx] = cell.is_alive();
+        }
+        self.next_alive.copy_from_slice(&self.scratch_alive);
+        self.next_energy.copy_from_slice(&self.energy);
+        self.next_resources.copy_from_slice(&self.resources);
+
+        for idx in 0..len {
+            if !self.scratch_alive[idx] {
+                continue;
+            }
+
+            let mut energy = self.energy[idx].saturating_sub(config.metabolic_cost);
+            energy = energy.saturating_add(self.resources[idx]);
+            self.next_resources[idx] = 0;
+
+            if energy == 0 {
+                self.next_alive[idx] = false;
+                self.next_energy[idx] = 0;
+                continue;
+            }
+
+            self.next_energy[idx] = energy;
+
+            if energy > config.birth_threshold {
+                // Distinct salt from the resource-regen draw above, so the
+                // two draws for the same cell/tick aren't correlated.
+                let draw = cell_random(seed, launch_count, iteration ^ 0x5345_4c45_4354, idx as u64);
+
+                // Empty neighbours fit in a fixed-size array (there are at
+                // most 8), so picking a birth target needs no allocation.
+                let mut empty_neighbours = [0usize; 8];
+                let mut empty_count = 0;
+                for n in wrapped_neighbour_indices(width, height, idx) {
+                    if !self.scratch_alive[n] && !self.next_alive[n] {
+                        empty_neighbours[empty_count] = n;
+                        empty_count += 1;
+                    }
+                }
+
+                if empty_count > 0 {
+                    let choice = ((draw * empty_count as f64) as usize).min(empty_count - 1);
+                    let target = empty_neighbours[choice];
+
+                    let half = energy / 2;
+                    self.next_energy[idx] = half;
+                    self.next_alive[target] = true;
+                    self.next_energy[target] = half;
+                    self.next_resources[target] = 0;
+                }
+            }
+        }
+
+        for (idx, cell) in self.cells.front_mut().iter_mut().enumerate() {
+            cell.set_state(self.next_alive[idx]);
+        }
+        self.energy.copy_from_slice(&self.next_energy);
+        self.resources.copy_from_slice(&self.next_resources);
+    }
+
     /// Converts a pair of cell coords to index in the cells vector
     pub fn coords_to_index(&self, pos: Point) -> usize {
-        pos.y * self.width + pos.x
+        coords_to_index(self.width, pos)
     }
 
     /// Converts a index in the cells vecotr into pair of cell coords
     pub fn index_to_coords(&self, index: usize) -> Point {
-        Point {
-            x: index % self.height,
-            y: index / self.width,
+        index_to_coords(self.width, index)
+    }
+}
+
+/// Parses an RLE header line (`x = W, y = H, ...`) into the pattern's
+/// `(width, height)`.
+fn parse_rle_header(header: &str) -> Result<(usize, usize), String> {
+    let mut width = None;
+    let mut height = None;
+
+    for part in header.split(',') {
+        let mut kv = part.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim();
+        let value = kv.next().unwrap_or("").trim();
+
+        match key {
+            "x" => width = value.parse::<usize>().ok(),
+            "y" => height = value.parse::<usize>().ok(),
+            _ => {}
         }
     }
+
+    match (width, height) {
+        (Some(w), Some(h)) => Ok((w, h)),
+        _ => Err("invalid RLE header, expected \"x = .., y = ..\"".to_string()),
+    }
+}
+
+fn coords_to_index(width: usize, pos: Point) -> usize {
+    pos.y * width + pos.x
+}
+
+fn index_to_coords(width: usize, index: usize) -> Point {
+    Point {
+        x: index % width,
+        y: index / width,
+    }
+}
+
+/// Returns the indices of the 8 cells wrapped around `cell_idx` on a
+/// `width` x `height` torus. Free function (rather than a `Grid` method) so
+/// it can be called from `next_cell_state` while the front/back cell
+/// buffers are independently borrowed.
+fn wrapped_neighbour_indices(width: usize, height: usize, cell_idx: usize) -> [usize; 8] {
+    let cell_pos = index_to_coords(width, cell_idx);
+    let mut neighbours = [0usize; 8];
+    let mut i = 0;
+    for &x_off in [-1, 0, 1].iter() {
+        for &y_off in [-1, 0, 1].iter() {
+            if x_off == 0 && y_off == 0 {
+                continue;
+            }
+            let neighbour_pos;
+            let neighbour_coords = (cell_pos.x as isize + x_off, cell_pos.y as isize + y_off);
+
+            // Make torus
+            if neighbour_coords.0 < 0 {
+                // top-left cell
+                if neighbour_coords.1 < 0 {
+                    neighbour_pos = Point {
+                        x: width - 1,
+                        y: height - 1,
+                    }
+                } else if neighbour_coords.1 > height as isize - 1 {
+                    // bottom-left cell
+                    neighbour_pos = Point { x: width - 1, y: 0 }
+                } else {
+                    // left cell
+                    neighbour_pos = Point {
+                        x: width - 1,
+                        y: neighbour_coords.1 as usize,
+                    }
+                }
+            } else if neighbour_coords.0 > width as isize - 1 {
+                if neighbour_coords.1 < 0 {
+                    // top-right cell
+                    neighbour_pos = Point {
+                        x: 0,
+                        y: height - 1,
+                    }
+                } else if neighbour_coords.1 > height as isize - 1 {
+                    // bottom-right cell
+                    neighbour_pos = Point { x: 0, y: 0 }
+                } else {
+                    // right cell
+                    neighbour_pos = Point {
+                        x: 0,
+                        y: neighbour_coords.1 as usize,
+                    }
+                }
+            } else if neighbour_coords.1 < 0 {
+                // top cell
+                neighbour_pos = Point {
+                    x: neighbour_coords.0 as usize,
+                    y: height - 1,
+                }
+            } else if neighbour_coords.1 > height as isize - 1 {
+                // bottom cell
+                neighbour_pos = Point {
+                    x: neighbour_coords.0 as usize,
+                    y: 0,
+                }
+            } else {
+                // Others cells
+                neighbour_pos = Point {
+                    x: neighbour_coords.0 as usize,
+                    y: neighbour_coords.1 as usize,
+                };
+            }
+
+            neighbours[i] = coords_to_index(width, neighbour_pos);
+            i += 1;
+        }
+    }
+    neighbours
+}
+
+/// Computes the next state of a single cell given the front buffer to read
+/// from. Free function (rather than a `Grid` method) so `Grid::step_life` can
+/// call it while the front buffer and the back buffer it writes into are
+/// borrowed independently via `DoubleBuffer::split_mut`.
+#[allow(clippy::too_many_arguments)]
+fn next_cell_state(
+    front: &[Cell],
+    width: usize,
+    height: usize,
+    birth: &[bool; 9],
+    survival: &[bool; 9],
+    alive_probability: f64,
+    dead_probability: f64,
+    seed: u64,
+    launch_count: u64,
+    iteration: u64,
+    cell_idx: usize,
+) -> bool {
+    let cell = &front[cell_idx];
+    // Check boundaries and add neighgours
+    let mut num_neighbour_alive = 0;
+    for idx in wrapped_neighbour_indices(width, height, cell_idx).iter() {
+        if front[*idx].is_alive() {
+            num_neighbour_alive += 1;
+        }
+    }
+
+    let probability = cell_random(seed, launch_count, iteration, cell_idx as u64);
+
+    // Deterministic target state from the configured birth/survival rule,
+    // see https://en.wikipedia.org/wiki/Conway%27s_Game_of_Life for the rulestring notation
+    let target_alive = if cell.is_alive() {
+        survival[num_neighbour_alive]
+    } else {
+        birth[num_neighbour_alive]
+    };
+
+    if target_alive {
+        if probability <= alive_probability {
+            return true; // alive
+        }
+
+        return false;
+    }
+
+    if probability <= dead_probability {
+        return false;
+    }
+
+    return true;
+}
+
+/// Draws a reproducible pseudo-random value in `[0, 1]` for a single cell on
+/// a single tick, as a pure function of the grid's seed, launch count,
+/// iteration and cell index. This keeps the parallel update deterministic
+/// and independent of rayon's thread scheduling, so a stochastic experiment
+/// can be rerun identically.
+fn cell_random(seed: u64, launch_count: u64, iteration: u64, cell_idx: u64) -> f64 {
+    let mut h = seed;
+    h = splitmix64(h ^ launch_count);
+    h = splitmix64(h ^ iteration);
+    h = splitmix64(h ^ cell_idx);
+    h as f64 / u64::MAX as f64
+}
+
+/// A fast, well-mixed 64-bit hash (splitmix64). Used to turn `(seed, launch_count,
+/// iteration, cell_idx)` tuples into independent-looking pseudo-random draws
+/// without keeping any RNG state around.
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_rule_rejects_out_of_range_digit() {
+        let mut grid = Grid::new(3, 3, 1, 1, 0.0, 0.0, "B3/S23", 0, None);
+        assert!(grid.set_rule("B9/S23").is_err());
+        assert!(grid.set_rule("B36/S23").is_ok());
+    }
+
+    #[test]
+    fn rle_round_trip_glider() {
+        let mut grid = Grid::new(10, 10, 1, 1, 0.0, 0.0, "B3/S23", 0, None);
+        grid.from_rle("x = 3, y = 3\nbob$2bo$3o!").unwrap();
+
+        let alive = grid.cells().iter().filter(|c| c.is_alive()).count();
+        assert_eq!(alive, 5);
+
+        let text = grid.to_plaintext();
+        assert_eq!(text.lines().count(), 10);
+    }
+
+    #[test]
+    fn from_rle_rejects_body_wider_than_header() {
+        let mut grid = Grid::new(10, 10, 1, 1, 0.0, 0.0, "B3/S23", 0, None);
+        assert!(grid.from_rle("x = 2, y = 1\n3o!").is_err());
+    }
+
+    #[test]
+    fn label_regions_counts_two_blobs() {
+        let mut grid = Grid::new(6, 6, 1, 1, 0.0, 0.0, "B3/S23", 0, None);
+        grid.set_state(&[
+            Point { x: 0, y: 0 },
+            Point { x: 1, y: 0 },
+            Point { x: 3, y: 3 },
+            Point { x: 4, y: 3 },
+            Point { x: 4, y: 4 },
+        ]);
+
+        let (_labels, mut sizes) = grid.label_regions();
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![2, 3]);
+    }
+
+    #[test]
+    fn update_is_reproducible_given_same_seed() {
+        let cells = [
+            Point { x: 1, y: 1 },
+            Point { x: 2, y: 2 },
+            Point { x: 3, y: 1 },
+        ];
+        let mut a = Grid::new(8, 8, 50, 1, 0.3, 0.3, "B3/S23", 123, None);
+        let mut b = Grid::new(8, 8, 50, 1, 0.3, 0.3, "B3/S23", 123, None);
+        a.set_initial_state(&cells);
+        b.set_initial_state(&cells);
+
+        for _ in 0..10 {
+            a.update();
+            b.update();
+        }
+
+        let states_a: Vec<bool> = a.cells().iter().map(|c| c.is_alive()).collect();
+        let states_b: Vec<bool> = b.cells().iter().map(|c| c.is_alive()).collect();
+        assert_eq!(states_a, states_b);
+    }
+
+    #[test]
+    fn step_agents_starves_cell_to_death() {
+        let config = AgentModeConfig {
+            resource_regen_probability: 0.0,
+            resource_cap: 0,
+            metabolic_cost: 5,
+            birth_threshold: u32::MAX,
+            initial_energy: 3,
+        };
+        let mut grid = Grid::new(3, 3, 100, 1, 0.0, 0.0, "B3/S23", 42, Some(config));
+        grid.set_state(&[Point { x: 1, y: 1 }]);
+        let idx = grid.coords_to_index(Point { x: 1, y: 1 });
+        assert!(grid.cells()[idx].is_alive());
+
+        grid.update();
+
+        assert!(!grid.cells()[idx].is_alive());
+        assert_eq!(grid.energy[idx], 0);
+    }
+
+    #[test]
+    fn step_agents_splits_above_birth_threshold() {
+        let config = AgentModeConfig {
+            resource_regen_probability: 0.0,
+            resource_cap: 0,
+            metabolic_cost: 0,
+            birth_threshold: 1,
+            initial_energy: 10,
+        };
+        let mut grid = Grid::new(5, 5, 100, 1, 0.0, 0.0, "B3/S23", 7, Some(config));
+        grid.set_state(&[Point { x: 2, y: 2 }]);
+
+        grid.update();
+
+        let alive_count = grid.cells().iter().filter(|c| c.is_alive()).count();
+        assert_eq!(alive_count, 2);
+        assert_eq!(grid.energy.iter().sum::<u32>(), 10);
+    }
 }